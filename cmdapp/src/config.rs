@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use visioncortex::PathSimplifyMode;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    Color,
+    Binary,
+    Seg,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hierarchical {
+    Stacked,
+    Cutout,
+}
+
+/// User-facing configuration, as collected from CLI flags or library callers.
+#[derive(Clone)]
+pub struct Config {
+    pub input_path: PathBuf,
+    pub output_path: PathBuf,
+    pub color_mode: ColorMode,
+    pub hierarchical: Hierarchical,
+    pub filter_speckle: usize,
+    pub color_precision: i32,
+    pub layer_difference: i32,
+    pub mode: PathSimplifyMode,
+    pub corner_threshold: i32,
+    pub length_threshold: f64,
+    pub max_iterations: usize,
+    pub splice_threshold: i32,
+    pub path_precision: u32,
+    /// Cap the number of flat colors in the output via median-cut quantization before
+    /// clustering. `None` leaves the full color range from `color_precision` alone.
+    pub max_colors: Option<usize>,
+    /// How hard to try shrinking the final SVG by re-emitting path geometry at several
+    /// coordinate precisions and simplification epsilons and keeping the smallest. `0` disables
+    /// the optimizer entirely; levels `1`-`3` each widen the search and tolerate more
+    /// simplification error than the last; levels above `3` search the same widest range as `3`.
+    pub optimize_level: u8,
+    /// Emit a shared `<defs>`/CSS-class palette instead of inlining `fill="#rrggbb"` on every
+    /// path. Smaller for images that produce many small, repeated-color clusters.
+    pub shared_palette: bool,
+}
+
+impl Config {
+    pub fn into_converter_config(self) -> ConverterConfig {
+        ConverterConfig {
+            color_mode: self.color_mode,
+            hierarchical: self.hierarchical,
+            filter_speckle_area: self.filter_speckle * self.filter_speckle,
+            color_precision_loss: 1 << (8 - self.color_precision),
+            layer_difference: self.layer_difference,
+            mode: self.mode,
+            corner_threshold: (self.corner_threshold as f64).to_radians(),
+            length_threshold: self.length_threshold,
+            max_iterations: self.max_iterations,
+            splice_threshold: (self.splice_threshold as f64).to_radians(),
+            max_error_simp: self.length_threshold,
+            path_precision: self.path_precision,
+            max_colors: self.max_colors,
+            shared_palette: self.shared_palette,
+            optimize_level: self.optimize_level,
+        }
+    }
+}
+
+/// The normalized, ready-to-run counterpart of [`Config`] consumed by `converter`.
+pub struct ConverterConfig {
+    pub color_mode: ColorMode,
+    pub hierarchical: Hierarchical,
+    pub filter_speckle_area: usize,
+    pub color_precision_loss: i32,
+    pub layer_difference: i32,
+    pub mode: PathSimplifyMode,
+    pub corner_threshold: f64,
+    pub length_threshold: f64,
+    pub max_iterations: usize,
+    pub splice_threshold: f64,
+    pub max_error_simp: f64,
+    pub path_precision: u32,
+    pub max_colors: Option<usize>,
+    pub shared_palette: bool,
+    pub optimize_level: u8,
+}
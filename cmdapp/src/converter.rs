@@ -3,7 +3,7 @@ use std::{fs::File, io::Write};
 use std::time::Instant;
 
 use super::config::{ColorMode, Config, ConverterConfig, Hierarchical};
-use super::svg::SvgFile;
+use super::svg::{FillMode, SvgFile};
 use fastrand::Rng;
 use opencv::prelude::*;
 use visioncortex::color_clusters::{KeyingAction, Runner, RunnerConfig, HIERARCHICAL_MAX};
@@ -54,6 +54,86 @@ pub fn convert_image_to_svg(
     write_svg(svg, output_path)
 }
 
+/// `optimize_level` tiers beyond this behave identically to it — the search doesn't keep
+/// widening forever just because a caller passes a large `u8`.
+const MAX_OPTIMIZE_TIER: u8 = 3;
+/// `max_error_simp` scale factors unlocked one at a time as `optimize_level` rises from `1` to
+/// `MAX_OPTIMIZE_TIER`. Tier `N` tries the baseline plus `OPTIMIZE_ERROR_SCALES[..N]`, so each
+/// higher level strictly adds a larger, more tolerant candidate instead of swapping in an
+/// unrelated fixed set.
+const OPTIMIZE_ERROR_SCALES: [f64; MAX_OPTIMIZE_TIER as usize] = [1.5, 2.0, 3.0];
+
+/// `path_precision` candidates to try when building an `SvgFile`, given the configured baseline
+/// and how hard `optimize_level` says to try. `0` (optimizer off) means only the baseline; each
+/// higher tier (up to `MAX_OPTIMIZE_TIER`) widens the search by one more coordinate step in both
+/// directions.
+fn optimize_precisions(base_path_precision: u32, optimize_level: u8) -> Vec<u32> {
+    let tier = i64::from(optimize_level.min(MAX_OPTIMIZE_TIER));
+    if tier == 0 {
+        return vec![base_path_precision];
+    }
+    (-tier..=tier)
+        .filter_map(|scale| u32::try_from(i64::from(base_path_precision) + scale).ok())
+        .collect()
+}
+
+/// `max_error_simp` candidates to try, given the configured baseline Douglas-Peucker tolerance
+/// already used by `Cluster::to_compound_path`. `0` (optimizer off) means only the baseline; each
+/// higher tier (up to `MAX_OPTIMIZE_TIER`) also tries the next, larger scale factor from
+/// `OPTIMIZE_ERROR_SCALES`.
+fn optimize_max_error_simps(base_max_error_simp: f64, optimize_level: u8) -> Vec<f64> {
+    let tier = optimize_level.min(MAX_OPTIMIZE_TIER) as usize;
+    let mut max_error_simps = vec![base_max_error_simp];
+    max_error_simps.extend(
+        OPTIMIZE_ERROR_SCALES[..tier]
+            .iter()
+            .map(|scale| base_max_error_simp * scale),
+    );
+    max_error_simps
+}
+
+/// Build one `SvgFile` per `max_error_simp` via `build`, then re-serialize it at every
+/// `path_precision` candidate and keep whichever `(path_precision, max_error_simp)` pair
+/// serializes smallest. `path_precision` only affects `to_svg_string`'s rounding at serialization
+/// time, not path geometry (see `svg.rs`), so this tries every pair while building each
+/// `CompoundPath` only once per `max_error_simp`. Mirrors oxipng's "try several encodings, keep
+/// the smallest" strategy, applied to path geometry and coordinate precision instead of pixel
+/// filters.
+fn smallest_svg(
+    path_precisions: &[u32],
+    max_error_simps: &[f64],
+    mut build: impl FnMut(f64) -> SvgFile,
+) -> SvgFile {
+    let mut best: Option<(usize, SvgFile)> = None;
+    for &max_error_simp in max_error_simps {
+        let mut svg = build(max_error_simp);
+
+        let mut best_for_geometry: Option<(usize, u32)> = None;
+        for &path_precision in path_precisions {
+            svg.set_path_precision(path_precision);
+            let size = svg.serialized_len();
+            if best_for_geometry.map_or(true, |(best_size, _)| size < best_size) {
+                best_for_geometry = Some((size, path_precision));
+            }
+        }
+        let (size, path_precision) = best_for_geometry.expect("path_precisions is non-empty");
+        svg.set_path_precision(path_precision);
+
+        if best.as_ref().map_or(true, |(best_size, _)| size < *best_size) {
+            best = Some((size, svg));
+        }
+    }
+    best.map(|(_, svg)| svg).expect("at least one candidate")
+}
+
+fn fill_mode(config: &ConverterConfig) -> FillMode {
+    if config.shared_palette {
+        FillMode::SharedPalette
+    } else {
+        FillMode::Inline
+    }
+}
+
 fn color_exists_in_image(img: &ColorImage, color: Color) -> bool {
     for y in 0..img.height {
         for x in 0..img.width {
@@ -117,11 +197,129 @@ fn should_key_image(img: &ColorImage) -> bool {
     false
 }
 
+/// Which RGB channel a median-cut box in [`quantize_colors`] is widest along.
+#[derive(Clone, Copy)]
+enum Channel {
+    R,
+    G,
+    B,
+}
+
+fn channel_value(color: Color, channel: Channel) -> u8 {
+    match channel {
+        Channel::R => color.r,
+        Channel::G => color.g,
+        Channel::B => color.b,
+    }
+}
+
+fn pixel_index(img: &ColorImage, x: usize, y: usize) -> usize {
+    y * img.width + x
+}
+
+fn pixel_at_index(img: &ColorImage, index: usize) -> Color {
+    img.get_pixel(index % img.width, index / img.width)
+}
+
+/// The longest channel axis (by max-min) spanned by a box's member pixels, along with its range.
+fn longest_axis(img: &ColorImage, indices: &[usize]) -> (Channel, u8) {
+    let mut min = [u8::MAX; 3];
+    let mut max = [0u8; 3];
+    for &index in indices {
+        let pixel = pixel_at_index(img, index);
+        for (channel, value) in [pixel.r, pixel.g, pixel.b].into_iter().enumerate() {
+            min[channel] = min[channel].min(value);
+            max[channel] = max[channel].max(value);
+        }
+    }
+    let ranges = [max[0] - min[0], max[1] - min[1], max[2] - min[2]];
+    let (axis, &range) = ranges
+        .iter()
+        .enumerate()
+        .max_by_key(|&(_, range)| *range)
+        .expect("ranges is non-empty");
+    let channel = match axis {
+        0 => Channel::R,
+        1 => Channel::G,
+        _ => Channel::B,
+    };
+    (channel, range)
+}
+
+fn mean_color(img: &ColorImage, indices: &[usize]) -> Color {
+    let (mut r, mut g, mut b) = (0u32, 0u32, 0u32);
+    for &index in indices {
+        let pixel = pixel_at_index(img, index);
+        r += pixel.r as u32;
+        g += pixel.g as u32;
+        b += pixel.b as u32;
+    }
+    let n = indices.len() as u32;
+    Color::new((r / n) as u8, (g / n) as u8, (b / n) as u8)
+}
+
+/// Collapse `img` to at most `max_colors` flat shades using median-cut quantization, so near
+/// duplicate shades merge into stable clusters instead of depending entirely on
+/// `color_precision_loss`. Pixels already flattened to `key_color` (or fully transparent) are
+/// left untouched so keying still works. `key_color` is `None` when keying isn't engaged (see the
+/// `Color::default()` "no keying" sentinel in `color_image_to_svg`) — in that case no pixel is
+/// excluded on color grounds, so ordinary opaque content that happens to match the zero sentinel
+/// (e.g. pure black) still gets quantized.
+fn quantize_colors(img: &mut ColorImage, max_colors: usize, key_color: Option<Color>) {
+    if max_colors == 0 {
+        return;
+    }
+
+    let mut boxes = vec![(0..img.width * img.height)
+        .filter(|&index| {
+            let pixel = pixel_at_index(img, index);
+            pixel.a != 0
+                && !key_color.is_some_and(|key_color| {
+                    pixel.r == key_color.r && pixel.g == key_color.g && pixel.b == key_color.b
+                })
+        })
+        .collect::<Vec<usize>>()];
+
+    while boxes.len() < max_colors {
+        let Some((widest, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, indices)| indices.len() >= 2)
+            .max_by_key(|(_, indices)| longest_axis(img, indices).1)
+        else {
+            break;
+        };
+
+        let mut to_split = boxes.swap_remove(widest);
+        let (channel, range) = longest_axis(img, &to_split);
+        if range == 0 {
+            boxes.push(to_split);
+            break;
+        }
+        to_split.sort_unstable_by_key(|&index| channel_value(pixel_at_index(img, index), channel));
+        let second_half = to_split.split_off(to_split.len() / 2);
+        boxes.push(to_split);
+        boxes.push(second_half);
+    }
+
+    for indices in &boxes {
+        if indices.is_empty() {
+            continue;
+        }
+        let representative = mean_color(img, indices);
+        for &index in indices {
+            let (x, y) = (index % img.width, index / img.width);
+            debug_assert_eq!(pixel_index(img, x, y), index);
+            img.set_pixel(x, y, &representative);
+        }
+    }
+}
+
 fn color_image_to_svg(mut img: ColorImage, config: ConverterConfig) -> Result<SvgFile, String> {
     let width = img.width;
     let height = img.height;
 
-    let key_color = if should_key_image(&img) {
+    let (key_color, keying_active) = if should_key_image(&img) {
         let key_color = find_unused_color_in_image(&img)?;
         for y in 0..height {
             for x in 0..width {
@@ -130,12 +328,24 @@ fn color_image_to_svg(mut img: ColorImage, config: ConverterConfig) -> Result<Sv
                 }
             }
         }
-        key_color
+        (key_color, true)
     } else {
         // The default color is all zeroes, which is treated by visioncortex as a special value meaning no keying will be applied.
-        Color::default()
+        (Color::default(), false)
     };
 
+    // Captured before quantization (which flattens every pixel in a box to one representative
+    // color, alpha included) and before the image is moved into the Runner, so fully-opaque-
+    // looking clusters can still report the original soft-edge alpha of the pixels that formed
+    // them.
+    let alpha_plane: Vec<u8> = (0..width * height)
+        .map(|index| img.get_pixel(index % width, index / width).a)
+        .collect();
+
+    if let Some(max_colors) = config.max_colors {
+        quantize_colors(&mut img, max_colors, keying_active.then_some(key_color));
+    }
+
     let runner = Runner::new(
         RunnerConfig {
             diagonal: config.layer_difference == 0,
@@ -189,34 +399,70 @@ fn color_image_to_svg(mut img: ColorImage, config: ConverterConfig) -> Result<Sv
     // println!("{}", view.clusters_output.len());
     // println!("{}", clusters.output_len());
 
-    let mut svg = SvgFile::new(width, height, config.path_precision);
-    for &cluster_index in view.clusters_output.iter().rev() {
-        let cluster = view.get_cluster(cluster_index);
-        let paths = if matches!(config.mode, PathSimplifyMode::Spline)
-            // && cluster.rect.width() < SMALL_CIRCLE
-            // && cluster.rect.height() < SMALL_CIRCLE
-            && cluster.to_shape(&view).is_circle()
-        {
-            let mut paths = CompoundPath::new();
-            paths.add_spline(approximate_circle_with_spline(
-                cluster.rect.left_top(),
-                cluster.rect.width(),
-            ));
-            paths
-        } else {
-            cluster.to_compound_path(
-                &view,
-                false,
-                config.mode,
-                config.corner_threshold,
-                config.length_threshold,
-                config.max_iterations,
-                config.splice_threshold,
-                config.max_error_simp,
-            )
-        };
-        svg.add_path(paths, cluster.residue_color());
-    }
+    // Ordering and per-cluster alpha don't depend on `max_error_simp`/`path_precision`, so they're
+    // computed once and reused by every candidate the size optimizer below builds.
+    let cluster_indices: Vec<_> = view.clusters_output.iter().rev().copied().collect();
+    let cluster_alphas: Vec<u8> = cluster_indices
+        .iter()
+        .map(|&cluster_index| {
+            let cluster = view.get_cluster(cluster_index);
+            let shape = cluster.to_shape(&view);
+            let top_left = cluster.rect.left_top();
+            let (mut alpha_sum, mut alpha_count) = (0u32, 0u32);
+            for local_y in 0..cluster.rect.height() {
+                for local_x in 0..cluster.rect.width() {
+                    if shape.get_pixel(local_x, local_y) {
+                        let x = (top_left.x + local_x) as usize;
+                        let y = (top_left.y + local_y) as usize;
+                        alpha_sum += alpha_plane[y * width + x] as u32;
+                        alpha_count += 1;
+                    }
+                }
+            }
+            if alpha_count == 0 {
+                255
+            } else {
+                (alpha_sum / alpha_count) as u8
+            }
+        })
+        .collect();
+
+    let path_precisions = optimize_precisions(config.path_precision, config.optimize_level);
+    let max_error_simps = optimize_max_error_simps(config.max_error_simp, config.optimize_level);
+
+    let svg = smallest_svg(&path_precisions, &max_error_simps, |max_error_simp| {
+        // `path_precision` is irrelevant here; `smallest_svg` overwrites it via
+        // `set_path_precision` before ever serializing this candidate.
+        let mut svg = SvgFile::new(width, height, 0, fill_mode(&config));
+        for (&cluster_index, &alpha) in cluster_indices.iter().zip(cluster_alphas.iter()) {
+            let cluster = view.get_cluster(cluster_index);
+            let paths = if matches!(config.mode, PathSimplifyMode::Spline)
+                // && cluster.rect.width() < SMALL_CIRCLE
+                // && cluster.rect.height() < SMALL_CIRCLE
+                && cluster.to_shape(&view).is_circle()
+            {
+                let mut paths = CompoundPath::new();
+                paths.add_spline(approximate_circle_with_spline(
+                    cluster.rect.left_top(),
+                    cluster.rect.width(),
+                ));
+                paths
+            } else {
+                cluster.to_compound_path(
+                    &view,
+                    false,
+                    config.mode,
+                    config.corner_threshold,
+                    config.length_threshold,
+                    config.max_iterations,
+                    config.splice_threshold,
+                    max_error_simp,
+                )
+            };
+            svg.add_path_with_alpha(paths, cluster.residue_color(), alpha);
+        }
+        svg
+    });
 
     Ok(svg)
 }
@@ -227,22 +473,31 @@ fn binary_image_to_svg(img: ColorImage, config: ConverterConfig) -> Result<SvgFi
     let height = img.height;
 
     let clusters = img.to_clusters(false);
-
-    let mut svg = SvgFile::new(width, height, config.path_precision);
-    for i in 0..clusters.len() {
-        let cluster = clusters.get_cluster(i);
-        if cluster.size() >= config.filter_speckle_area {
+    let cluster_indices: Vec<usize> = (0..clusters.len())
+        .filter(|&i| clusters.get_cluster(i).size() >= config.filter_speckle_area)
+        .collect();
+
+    let path_precisions = optimize_precisions(config.path_precision, config.optimize_level);
+    let max_error_simps = optimize_max_error_simps(config.max_error_simp, config.optimize_level);
+
+    let svg = smallest_svg(&path_precisions, &max_error_simps, |max_error_simp| {
+        // `path_precision` is irrelevant here; `smallest_svg` overwrites it via
+        // `set_path_precision` before ever serializing this candidate.
+        let mut svg = SvgFile::new(width, height, 0, fill_mode(&config));
+        for &i in &cluster_indices {
+            let cluster = clusters.get_cluster(i);
             let paths = cluster.to_compound_path(
                 config.mode,
                 config.corner_threshold,
                 config.length_threshold,
                 config.max_iterations,
                 config.splice_threshold,
-                config.max_error_simp,
+                max_error_simp,
             );
             svg.add_path(paths, Color::color(&ColorName::Black));
         }
-    }
+        svg
+    });
 
     Ok(svg)
 }
@@ -254,23 +509,36 @@ fn seg_image_to_svg(img: SegImage, config: ConverterConfig) -> Result<SvgFile, S
     // Use a HashSet to get unique values
 
     let clusters = img.to_clusters();
-    let mut svg = SvgFile::new(width, height, config.path_precision);
-    for i in 0..clusters.len() {
-        let cluster = clusters.get_cluster(i);
-        println!("cluster size {}", cluster.size());
-        if cluster.size() >= config.filter_speckle_area {
+    let cluster_indices: Vec<usize> = (0..clusters.len())
+        .filter(|&i| {
+            let cluster = clusters.get_cluster(i);
+            println!("cluster size {}", cluster.size());
+            cluster.size() >= config.filter_speckle_area
+        })
+        .collect();
+
+    let path_precisions = optimize_precisions(config.path_precision, config.optimize_level);
+    let max_error_simps = optimize_max_error_simps(config.max_error_simp, config.optimize_level);
+
+    let svg = smallest_svg(&path_precisions, &max_error_simps, |max_error_simp| {
+        // `path_precision` is irrelevant here; `smallest_svg` overwrites it via
+        // `set_path_precision` before ever serializing this candidate.
+        let mut svg = SvgFile::new(width, height, 0, fill_mode(&config));
+        for &i in &cluster_indices {
+            let cluster = clusters.get_cluster(i);
             let paths = cluster.to_compound_path(
                 config.mode,
                 config.corner_threshold,
                 config.length_threshold,
                 config.max_iterations,
                 config.splice_threshold,
-                config.max_error_simp,
+                max_error_simp,
             );
             // let (string, offset) = paths.to_svg_string(true, PointF64 { x: 0.0, y: 0.0 }, None);
             svg.add_path(paths, Color::color(&ColorName::Black));
         }
-    }
+        svg
+    });
 
     Ok(svg)
 }
@@ -338,3 +606,109 @@ fn write_svg(svg: SvgFile, output_path: &Path) -> Result<(), String> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba_image(width: usize, height: usize, pixels: Vec<u8>) -> ColorImage {
+        assert_eq!(pixels.len(), width * height * 4);
+        ColorImage {
+            pixels,
+            width,
+            height,
+        }
+    }
+
+    fn rgb(img: &ColorImage, x: usize, y: usize) -> (u8, u8, u8) {
+        let pixel = img.get_pixel(x, y);
+        (pixel.r, pixel.g, pixel.b)
+    }
+
+    #[test]
+    fn quantize_colors_leaves_already_uniform_image_alone() {
+        let color = Color::new(10, 20, 30);
+        let mut img = rgba_image(
+            2,
+            2,
+            vec![
+                color.r, color.g, color.b, 255, color.r, color.g, color.b, 255, color.r, color.g,
+                color.b, 255, color.r, color.g, color.b, 255,
+            ],
+        );
+
+        quantize_colors(&mut img, 1, None);
+
+        for y in 0..2 {
+            for x in 0..2 {
+                assert_eq!(rgb(&img, x, y), (color.r, color.g, color.b));
+            }
+        }
+    }
+
+    #[test]
+    fn quantize_colors_merges_two_colors_into_their_mean() {
+        let mut img = rgba_image(
+            2,
+            2,
+            vec![
+                0, 0, 0, 255, // black
+                0, 0, 0, 255, // black
+                100, 100, 100, 255, // grey
+                100, 100, 100, 255, // grey
+            ],
+        );
+
+        quantize_colors(&mut img, 1, None);
+
+        for y in 0..2 {
+            for x in 0..2 {
+                assert_eq!(rgb(&img, x, y), (50, 50, 50));
+            }
+        }
+    }
+
+    #[test]
+    fn quantize_colors_without_keying_still_quantizes_pure_black_pixels() {
+        // Regression test for ab2181f: a `None` key_color (keying not engaged) must not be
+        // confused with the `Color::default()` == (0, 0, 0, 0) "no keying" sentinel, which would
+        // otherwise silently exclude ordinary opaque black pixels from every quantization box.
+        let mut img = rgba_image(
+            2,
+            1,
+            vec![
+                0, 0, 0, 255, // opaque black, NOT a key pixel here
+                200, 200, 200, 255,
+            ],
+        );
+
+        quantize_colors(&mut img, 1, None);
+
+        assert_eq!(rgb(&img, 0, 0), (100, 100, 100));
+        assert_eq!(rgb(&img, 1, 0), (100, 100, 100));
+    }
+
+    #[test]
+    fn quantize_colors_skips_keyed_border_pixels() {
+        // The border has already been flattened to `key_color` by `color_image_to_svg` before
+        // `quantize_colors` runs; those pixels must be left untouched so keying still works.
+        let key_color = Color::new(255, 0, 255);
+        let mut img = rgba_image(
+            2,
+            2,
+            vec![
+                key_color.r, key_color.g, key_color.b, 255, //
+                10, 20, 30, 255, //
+                40, 50, 60, 255, //
+                key_color.r, key_color.g, key_color.b, 255,
+            ],
+        );
+
+        quantize_colors(&mut img, 1, Some(key_color));
+
+        assert_eq!(rgb(&img, 0, 0), (key_color.r, key_color.g, key_color.b));
+        assert_eq!(rgb(&img, 1, 1), (key_color.r, key_color.g, key_color.b));
+        assert_eq!(rgb(&img, 1, 0), (25, 35, 45));
+        assert_eq!(rgb(&img, 0, 1), (25, 35, 45));
+    }
+}
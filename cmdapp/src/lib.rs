@@ -0,0 +1,7 @@
+pub mod config;
+pub mod converter;
+pub mod svg;
+
+pub use config::{ColorMode, Config, ConverterConfig, Hierarchical};
+pub use converter::{convert, convert_image_to_svg};
+pub use svg::{FillMode, SvgFile};
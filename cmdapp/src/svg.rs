@@ -0,0 +1,162 @@
+use std::fmt;
+
+use visioncortex::{Color, CompoundPath, PointF64};
+
+struct SvgPath {
+    path: CompoundPath,
+    color: Color,
+    /// Representative alpha of the cluster this path was traced from, 0-255. 255 (fully opaque)
+    /// is rendered without a `fill-opacity` attribute at all.
+    alpha: u8,
+}
+
+/// How path fills are serialized.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FillMode {
+    /// Repeat `fill="#rrggbb"` (and `fill-opacity`, if needed) on every `<path>`.
+    Inline,
+    /// Collect unique colors into `<defs><style>` CSS classes and reference them by class,
+    /// like an indexed palette. Smaller for images with many small, repeated-color clusters.
+    SharedPalette,
+}
+
+/// An in-memory representation of the SVG document being assembled by the converter.
+pub struct SvgFile {
+    width: usize,
+    height: usize,
+    path_precision: u32,
+    fill_mode: FillMode,
+    paths: Vec<SvgPath>,
+}
+
+impl SvgFile {
+    pub fn new(width: usize, height: usize, path_precision: u32, fill_mode: FillMode) -> Self {
+        Self {
+            width,
+            height,
+            path_precision,
+            fill_mode,
+            paths: vec![],
+        }
+    }
+
+    pub fn add_path(&mut self, path: CompoundPath, color: Color) {
+        self.add_path_with_alpha(path, color, 255);
+    }
+
+    /// Like [`add_path`](Self::add_path), but also records the cluster's representative alpha
+    /// so semi-transparent fills (soft edges, logos) survive instead of being flattened opaque.
+    pub fn add_path_with_alpha(&mut self, path: CompoundPath, color: Color, alpha: u8) {
+        self.paths.push(SvgPath { path, color, alpha });
+    }
+
+    /// Override the coordinate precision used when serializing paths, without touching their
+    /// geometry. Used by `converter`'s trial-based size optimizer to re-try `to_svg_string`
+    /// rounding at several precisions against the same already-built `CompoundPath`s.
+    pub fn set_path_precision(&mut self, path_precision: u32) {
+        self.path_precision = path_precision;
+    }
+
+    /// How many bytes this candidate serializes to. Used by `converter`'s trial-based size
+    /// optimizer to compare candidates built at different `(path_precision, max_error_simp)`
+    /// combinations without this module having to know anything about simplification.
+    pub fn serialized_len(&self) -> usize {
+        self.to_string().len()
+    }
+}
+
+fn color_to_hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+fn same_fill(a: (Color, u8), b: (Color, u8)) -> bool {
+    a.0.r == b.0.r && a.0.g == b.0.g && a.0.b == b.0.b && a.1 == b.1
+}
+
+impl SvgFile {
+    fn path_string(&self, svg_path: &SvgPath) -> String {
+        let (path_string, _) =
+            svg_path
+                .path
+                .to_svg_string(true, PointF64 { x: 0.0, y: 0.0 }, Some(self.path_precision));
+        path_string
+    }
+
+    fn fmt_inline(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for svg_path in self.paths.iter() {
+            let path_string = self.path_string(svg_path);
+            if svg_path.alpha < 255 {
+                writeln!(
+                    f,
+                    "<path d=\"{}\" fill=\"{}\" fill-opacity=\"{:.3}\" />",
+                    path_string,
+                    color_to_hex(svg_path.color),
+                    svg_path.alpha as f64 / 255.0
+                )?;
+            } else {
+                writeln!(
+                    f,
+                    "<path d=\"{}\" fill=\"{}\" />",
+                    path_string,
+                    color_to_hex(svg_path.color)
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn fmt_shared_palette(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut palette: Vec<(Color, u8)> = Vec::new();
+        let classes: Vec<usize> = self
+            .paths
+            .iter()
+            .map(|svg_path| {
+                let fill = (svg_path.color, svg_path.alpha);
+                match palette.iter().position(|&existing| same_fill(existing, fill)) {
+                    Some(index) => index,
+                    None => {
+                        palette.push(fill);
+                        palette.len() - 1
+                    }
+                }
+            })
+            .collect();
+
+        writeln!(f, "<defs><style>")?;
+        for (index, &(color, alpha)) in palette.iter().enumerate() {
+            if alpha < 255 {
+                writeln!(
+                    f,
+                    ".c{} {{ fill: {}; fill-opacity: {:.3}; }}",
+                    index,
+                    color_to_hex(color),
+                    alpha as f64 / 255.0
+                )?;
+            } else {
+                writeln!(f, ".c{} {{ fill: {}; }}", index, color_to_hex(color))?;
+            }
+        }
+        writeln!(f, "</style></defs>")?;
+
+        for (svg_path, &class) in self.paths.iter().zip(classes.iter()) {
+            let path_string = self.path_string(svg_path);
+            writeln!(f, "<path d=\"{}\" class=\"c{}\" />", path_string, class)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SvgFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+            self.width, self.height, self.width, self.height
+        )?;
+        match self.fill_mode {
+            FillMode::Inline => self.fmt_inline(f)?,
+            FillMode::SharedPalette => self.fmt_shared_palette(f)?,
+        }
+        writeln!(f, "</svg>")
+    }
+}